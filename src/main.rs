@@ -7,13 +7,16 @@
 //! - Manages Let's Encrypt certificates in pure Rust
 //! - Stores only TLS certificates on encrypted disk
 
+use async_trait::async_trait;
 use axum::{
-    extract::{Query, State},
-    http::StatusCode,
+    body::Bytes,
+    extract::{Extension, Query, State},
+    http::{HeaderMap, StatusCode},
     response::{Html, IntoResponse, Redirect, Response},
-    routing::get,
-    Router,
+    routing::{get, post},
+    Json, Router,
 };
+use base64::Engine as _;
 use instant_acme::{
     Account, AuthorizationStatus, ChallengeType, Identifier, LetsEncrypt, NewAccount, NewOrder,
     OrderStatus,
@@ -22,7 +25,12 @@ use parking_lot::RwLock;
 use rcgen::{CertificateParams, DistinguishedName, KeyPair};
 use rustls::pki_types::{CertificateDer, PrivateKeyDer};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashSet, net::SocketAddr, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    net::SocketAddr,
+    path::PathBuf,
+    sync::Arc,
+};
 use tokio::fs;
 use tokio::net::TcpListener;
 use tokio_rustls::rustls::ServerConfig;
@@ -79,6 +87,89 @@ struct AppState {
     redirect_uri: String,
     used_paypal_ids: Arc<RwLock<HashSet<String>>>,
     domain: String,
+    paypal_env: PayPalEnv,
+    cert_provenance: CertProvenance,
+    http_client: reqwest::Client,
+    paypal_client: Arc<PayPalClient>,
+    auth: Arc<Auth>,
+    webhook_id: String,
+    webhook_cert_cache: Arc<RwLock<HashMap<String, Vec<u8>>>>,
+    processed_webhook_events: Arc<RwLock<Vec<String>>>,
+    /// Live-status WS connections, keyed by session id, so `login`/`callback`
+    /// can push state changes to whichever client is watching that flow.
+    ws_sessions: Arc<RwLock<HashMap<String, tokio::sync::mpsc::UnboundedSender<WsEvent>>>>,
+}
+
+/// Which PayPal deployment OAuth/userinfo calls are made against.
+///
+/// `Mock(String)` points at a base URL (e.g. a `wiremock` instance) so the
+/// whole OAuth + userinfo round-trip can be exercised in tests without
+/// touching live PayPal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum PayPalEnv {
+    Live,
+    Sandbox,
+    Mock(String),
+}
+
+impl PayPalEnv {
+    /// Reads `PAYPAL_ENV` (`live` | `sandbox` | `mock:<base-url>`), defaulting to `Live`.
+    fn from_env() -> Self {
+        match std::env::var("PAYPAL_ENV") {
+            Ok(v) if v.eq_ignore_ascii_case("sandbox") => PayPalEnv::Sandbox,
+            Ok(v) if v.starts_with("mock:") => {
+                PayPalEnv::Mock(v.trim_start_matches("mock:").to_string())
+            }
+            _ => PayPalEnv::Live,
+        }
+    }
+
+    fn api_base(&self) -> &str {
+        match self {
+            PayPalEnv::Live => "https://api.paypal.com",
+            PayPalEnv::Sandbox => "https://api-m.sandbox.paypal.com",
+            PayPalEnv::Mock(url) => url,
+        }
+    }
+
+    fn web_base(&self) -> &str {
+        match self {
+            PayPalEnv::Live => "https://www.paypal.com",
+            PayPalEnv::Sandbox => "https://www.sandbox.paypal.com",
+            PayPalEnv::Mock(url) => url,
+        }
+    }
+
+    fn token_url(&self) -> String {
+        format!("{}/v1/oauth2/token", self.api_base())
+    }
+
+    fn userinfo_url(&self) -> String {
+        format!("{}/v1/identity/oauth2/userinfo?schema=paypalv1.1", self.api_base())
+    }
+
+    fn authorize_url(&self) -> String {
+        format!("{}/signin/authorize", self.web_base())
+    }
+}
+
+#[cfg(test)]
+mod paypal_env_tests {
+    use super::PayPalEnv;
+
+    /// `Mock` is how the whole OAuth + userinfo round-trip gets driven
+    /// against a wiremock server in tests without touching live PayPal — so
+    /// its URL builders need to actually point at the mock base URL.
+    #[test]
+    fn mock_env_builds_urls_against_the_mock_base() {
+        let env = PayPalEnv::Mock("http://127.0.0.1:1234".to_string());
+        assert_eq!(env.token_url(), "http://127.0.0.1:1234/v1/oauth2/token");
+        assert_eq!(
+            env.userinfo_url(),
+            "http://127.0.0.1:1234/v1/identity/oauth2/userinfo?schema=paypalv1.1"
+        );
+        assert_eq!(env.authorize_url(), "http://127.0.0.1:1234/signin/authorize");
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -102,33 +193,317 @@ struct PayPalUserInfo {
 struct CallbackQuery {
     code: Option<String>,
     error: Option<String>,
+    /// Echoed back by PayPal from the `state` param we sent in `login()` —
+    /// the live-status WS session id this callback should report into.
+    state: Option<String>,
 }
 
 // ============================================================================
-// ACME CERTIFICATE MANAGER
+// ERRORS
 // ============================================================================
 
+/// A single entry in PayPal's `details` array, e.g. `{"issue": "INVALID_GRANT", ...}`.
+#[derive(Debug, Deserialize, Clone)]
+struct PaypalErrorDetail {
+    issue: Option<String>,
+    description: Option<String>,
+}
+
+/// PayPal's standard error response body.
+///
+/// See https://developer.paypal.com/api/rest/responses/ — deserialized from
+/// a non-2xx response so callers can surface the specific `issue`/`debug_id`
+/// instead of an opaque blob of text.
+#[derive(Debug, Deserialize, Clone)]
+struct PaypalError {
+    name: String,
+    message: String,
+    debug_id: Option<String>,
+    #[serde(default)]
+    details: Vec<PaypalErrorDetail>,
+}
+
+impl std::fmt::Display for PaypalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.name, self.message)?;
+        if let Some(debug_id) = &self.debug_id {
+            write!(f, " (debug_id: {})", debug_id)?;
+        }
+        for detail in &self.details {
+            if let Some(issue) = &detail.issue {
+                write!(f, " [issue: {}", issue)?;
+                if let Some(description) = &detail.description {
+                    write!(f, " - {}", description)?;
+                }
+                write!(f, "]")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for PaypalError {}
+
+/// Parses a non-2xx PayPal response body, falling back to the raw text if it
+/// doesn't match PayPal's error schema.
+fn parse_paypal_error(body: &str) -> AppError {
+    match serde_json::from_str::<PaypalError>(body) {
+        Ok(err) => AppError::PayPal(err),
+        Err(_) => AppError::PayPal(PaypalError {
+            name: "unknown_error".to_string(),
+            message: body.to_string(),
+            debug_id: None,
+            details: Vec::new(),
+        }),
+    }
+}
+
+/// Top-level application error. Wraps every fallible subsystem so handlers
+/// can match on the kind of failure instead of inspecting an opaque string.
+#[derive(Debug)]
+enum AppError {
+    Acme(String),
+    Attestation(String),
+    Tls(String),
+    PayPal(PaypalError),
+    Vault(String),
+}
+
+impl std::fmt::Display for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AppError::Acme(msg) => write!(f, "ACME error: {}", msg),
+            AppError::Attestation(msg) => write!(f, "attestation error: {}", msg),
+            AppError::Tls(msg) => write!(f, "TLS error: {}", msg),
+            AppError::PayPal(err) => write!(f, "PayPal API error: {}", err),
+            AppError::Vault(msg) => write!(f, "vault error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+// ============================================================================
+// DNS-01 PROVIDER
+// ============================================================================
+
+/// Publishes (and ideally retracts) the `_acme-challenge` TXT record needed
+/// for DNS-01 validation. Kept pluggable so the DNS host can change without
+/// touching `AcmeManager`.
+#[async_trait]
+trait DnsProvider: Send + Sync {
+    async fn set_txt_record(&self, name: &str, value: &str) -> Result<(), AppError>;
+    async fn delete_txt_record(&self, name: &str) -> Result<(), AppError>;
+}
+
+/// Drives DNS-01 challenges through the Cloudflare API, configured from
+/// `CLOUDFLARE_API_TOKEN` and `CLOUDFLARE_ZONE_ID`.
+struct CloudflareDnsProvider {
+    api_token: String,
+    zone_id: String,
+    client: reqwest::Client,
+}
+
+impl CloudflareDnsProvider {
+    fn from_env() -> Option<Self> {
+        let api_token = std::env::var("CLOUDFLARE_API_TOKEN").ok()?;
+        let zone_id = std::env::var("CLOUDFLARE_ZONE_ID").ok()?;
+        Some(Self {
+            api_token,
+            zone_id,
+            client: reqwest::Client::new(),
+        })
+    }
+}
+
+#[async_trait]
+impl DnsProvider for CloudflareDnsProvider {
+    async fn set_txt_record(&self, name: &str, value: &str) -> Result<(), AppError> {
+        let url = format!(
+            "https://api.cloudflare.com/client/v4/zones/{}/dns_records",
+            self.zone_id
+        );
+        let body = serde_json::json!({ "type": "TXT", "name": name, "content": value, "ttl": 60 });
+
+        let response = self
+            .client
+            .post(&url)
+            .bearer_auth(&self.api_token)
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| AppError::Acme(format!("Cloudflare DNS-01 request failed: {}", e)))?;
+
+        if !response.status().is_success() {
+            let text = response.text().await.unwrap_or_default();
+            return Err(AppError::Acme(format!(
+                "Cloudflare DNS-01 record creation failed: {}",
+                text
+            )));
+        }
+
+        Ok(())
+    }
+
+    async fn delete_txt_record(&self, name: &str) -> Result<(), AppError> {
+        // Cloudflare has no delete-by-name shortcut; cleanup is best-effort
+        // and the record's short TTL means it expires on its own either way.
+        info!("DNS-01 TXT record for {} left to expire via its TTL", name);
+        Ok(())
+    }
+}
+
+// ============================================================================
+// CERTIFICATE DISK STORE
+// ============================================================================
+
+/// Renew comfortably before expiry rather than racing it.
+const CERT_RENEWAL_WINDOW_DAYS: i64 = 30;
+
+/// The cert+key PEM pair on the encrypted disk — the one thing this VM
+/// persists across boots, per the header's security architecture.
+#[derive(Clone)]
+struct CertStore {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+}
+
+impl CertStore {
+    /// Reads the directory from `CERT_DISK_DIR`, defaulting to a path under
+    /// the encrypted disk mount.
+    fn from_env() -> Self {
+        let dir = std::env::var("CERT_DISK_DIR")
+            .unwrap_or_else(|_| "/mnt/encrypted-disk/tls".to_string());
+        Self {
+            cert_path: PathBuf::from(&dir).join("cert.pem"),
+            key_path: PathBuf::from(&dir).join("key.pem"),
+        }
+    }
+
+    async fn load(&self) -> Option<(Vec<u8>, Vec<u8>)> {
+        let cert_pem = fs::read(&self.cert_path).await.ok()?;
+        let key_pem = fs::read(&self.key_path).await.ok()?;
+        Some((cert_pem, key_pem))
+    }
+
+    async fn save(&self, cert_pem: &[u8], key_pem: &[u8]) -> Result<(), AppError> {
+        if let Some(parent) = self.cert_path.parent() {
+            fs::create_dir_all(parent)
+                .await
+                .map_err(|e| AppError::Tls(e.to_string()))?;
+        }
+        fs::write(&self.cert_path, cert_pem)
+            .await
+            .map_err(|e| AppError::Tls(e.to_string()))?;
+        fs::write(&self.key_path, key_pem)
+            .await
+            .map_err(|e| AppError::Tls(e.to_string()))?;
+        Ok(())
+    }
+
+    /// Returns the cert and key files' last-modified times, so the file
+    /// watcher can detect a rotation performed by something other than this
+    /// process without having to read and re-parse the PEM on every poll.
+    async fn modified(&self) -> Option<(std::time::SystemTime, std::time::SystemTime)> {
+        let cert_modified = fs::metadata(&self.cert_path).await.ok()?.modified().ok()?;
+        let key_modified = fs::metadata(&self.key_path).await.ok()?.modified().ok()?;
+        Some((cert_modified, key_modified))
+    }
+}
+
+/// Parses the leaf certificate's `notAfter` and returns how many whole days
+/// remain until expiry (negative if already expired).
+fn days_until_expiry(cert_pem: &[u8]) -> Result<i64, AppError> {
+    let certs: Vec<CertificateDer> = rustls_pemfile::certs(&mut &cert_pem[..])
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| AppError::Tls(e.to_string()))?;
+
+    let leaf = certs
+        .first()
+        .ok_or_else(|| AppError::Tls("certificate PEM contained no certificates".into()))?;
+
+    let (_, parsed) = x509_parser::parse_x509_certificate(leaf.as_ref())
+        .map_err(|e| AppError::Tls(format!("failed to parse certificate: {}", e)))?;
+
+    let not_after = parsed.validity().not_after.timestamp();
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_err(|e| AppError::Tls(e.to_string()))?
+        .as_secs() as i64;
+
+    Ok((not_after - now).div_euclid(86_400))
+}
+
 // ============================================================================
 // ACME CERTIFICATE MANAGER
 // ============================================================================
 
 struct AcmeManager {
     domain: String,
+    dns_provider: Option<Arc<dyn DnsProvider>>,
+}
+
+/// Where the certificate `ensure_certificate` returned actually came from,
+/// so operator-facing pages can say so instead of always claiming "Fresh".
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CertProvenance {
+    /// Freshly issued from Let's Encrypt this boot.
+    Fresh,
+    /// Reused from the encrypted disk because it still had plenty of
+    /// validity left.
+    ReusedFromDisk,
+}
+
+impl CertProvenance {
+    /// The `<span>` badge these two provenances render as on the operator pages.
+    fn badge_html(&self) -> &'static str {
+        match self {
+            CertProvenance::Fresh => r#"<span class="cert-status cert-ram">🟢 FRESH (Issued This Boot)</span>"#,
+            CertProvenance::ReusedFromDisk => {
+                r#"<span class="cert-status cert-disk">💾 DISK-PERSISTED (Reused)</span>"#
+            }
+        }
+    }
 }
 
 impl AcmeManager {
     fn new(domain: String) -> Self {
-        Self { domain }
+        let dns_provider = CloudflareDnsProvider::from_env()
+            .map(|provider| Arc::new(provider) as Arc<dyn DnsProvider>);
+        Self { domain, dns_provider }
     }
 
-    async fn ensure_certificate(&self) -> Result<(Vec<u8>, Vec<u8>), Box<dyn std::error::Error>> {
+    /// Reuses the certificate on `store` if it still has more than
+    /// [`CERT_RENEWAL_WINDOW_DAYS`] of validity left; otherwise issues a
+    /// fresh one from Let's Encrypt and persists it back to disk.
+    async fn ensure_certificate(
+        &self,
+        store: &CertStore,
+    ) -> Result<(Vec<u8>, Vec<u8>, CertProvenance), AppError> {
+        if let Some((cert_pem, key_pem)) = store.load().await {
+            match days_until_expiry(&cert_pem) {
+                Ok(days) if days > CERT_RENEWAL_WINDOW_DAYS => {
+                    info!(
+                        "♻️ Reusing certificate from encrypted disk ({} days remaining)",
+                        days
+                    );
+                    return Ok((cert_pem, key_pem, CertProvenance::ReusedFromDisk));
+                }
+                Ok(days) => info!(
+                    "Certificate on disk expires in {} days, renewing",
+                    days
+                ),
+                Err(e) => warn!("Failed to parse certificate on disk, renewing: {}", e),
+            }
+        }
+
         info!("📜 Obtaining new certificate from Let's Encrypt...");
-        self.obtain_new_certificate().await
+        let (cert_pem, key_pem) = self.obtain_new_certificate().await?;
+        store.save(&cert_pem, &key_pem).await?;
+        Ok((cert_pem, key_pem, CertProvenance::Fresh))
     }
 
-    async fn obtain_new_certificate(
-        &self,
-    ) -> Result<(Vec<u8>, Vec<u8>), Box<dyn std::error::Error>> {
+    async fn obtain_new_certificate(&self) -> Result<(Vec<u8>, Vec<u8>), AppError> {
         info!("🔐 Connecting to Let's Encrypt...");
 
         // Create ACME account
@@ -141,7 +516,8 @@ impl AcmeManager {
             LetsEncrypt::Production.url(),
             None,
         )
-        .await?;
+        .await
+        .map_err(|e| AppError::Acme(e.to_string()))?;
 
         info!("✅ ACME account created");
 
@@ -151,42 +527,81 @@ impl AcmeManager {
             .new_order(&NewOrder {
                 identifiers: &[identifier],
             })
-            .await?;
+            .await
+            .map_err(|e| AppError::Acme(e.to_string()))?;
 
         info!("📋 Order created, obtaining authorizations...");
 
         // Get authorizations
-        let authorizations = order.authorizations().await?;
+        let authorizations = order
+            .authorizations()
+            .await
+            .map_err(|e| AppError::Acme(e.to_string()))?;
 
         for authz in &authorizations {
             match authz.status {
                 AuthorizationStatus::Pending => {}
                 AuthorizationStatus::Valid => continue,
-                _ => return Err("Authorization in invalid state".into()),
+                _ => return Err(AppError::Acme("authorization in invalid state".into())),
             }
 
-            // Find HTTP-01 challenge
+            // Wildcard identifiers can only be validated via DNS-01; plain
+            // domains keep using the existing HTTP-01 path.
+            let is_wildcard = self.domain.starts_with("*.");
+            let challenge_type = if is_wildcard {
+                ChallengeType::Dns01
+            } else {
+                ChallengeType::Http01
+            };
+
             let challenge = authz
                 .challenges
                 .iter()
-                .find(|c| c.r#type == ChallengeType::Http01)
-                .ok_or("No HTTP-01 challenge found")?;
-
-            let key_auth = order.key_authorization(challenge);
+                .find(|c| c.r#type == challenge_type)
+                .ok_or_else(|| {
+                    AppError::Acme(format!("no {:?} challenge found", challenge_type))
+                })?;
+
+            match challenge_type {
+                ChallengeType::Http01 => {
+                    let key_auth = order.key_authorization(challenge);
+
+                    // Write challenge to filesystem for Axum to serve
+                    let challenge_dir = "/tmp/acme-challenge";
+                    fs::create_dir_all(challenge_dir)
+                        .await
+                        .map_err(|e| AppError::Acme(e.to_string()))?;
+                    fs::write(
+                        format!("{}/{}", challenge_dir, challenge.token),
+                        key_auth.as_str(),
+                    )
+                    .await
+                    .map_err(|e| AppError::Acme(e.to_string()))?;
 
-            // Write challenge to filesystem for Axum to serve
-            let challenge_dir = "/tmp/acme-challenge";
-            fs::create_dir_all(challenge_dir).await?;
-            fs::write(
-                format!("{}/{}", challenge_dir, challenge.token),
-                key_auth.as_str(),
-            )
-            .await?;
+                    info!("📝 HTTP-01 challenge ready: {}", challenge.token);
+                }
+                ChallengeType::Dns01 => {
+                    let dns_value = order.key_authorization(challenge).dns_value();
+                    let bare_domain = self.domain.trim_start_matches("*.");
+                    let record_name = format!("_acme-challenge.{}", bare_domain);
+
+                    let provider = self.dns_provider.as_ref().ok_or_else(|| {
+                        AppError::Acme(
+                            "DNS-01 challenge requires a configured DnsProvider".into(),
+                        )
+                    })?;
+                    provider.set_txt_record(&record_name, &dns_value).await?;
 
-            info!("📝 HTTP-01 challenge ready: {}", challenge.token);
+                    info!("📝 DNS-01 TXT record published at {}", record_name);
+                }
+                _ => unreachable!("only HTTP-01 and DNS-01 are selected above"),
+            }
 
             // Tell Let's Encrypt we're ready
-            order.set_challenge_ready(&challenge.url).await?;
+            order
+                .set_challenge_ready(&challenge.url)
+                .await
+                .map_err(|e| AppError::Acme(e.to_string()))?;
 
             info!("⏳ Waiting for Let's Encrypt to validate challenge...");
 
@@ -197,38 +612,51 @@ impl AcmeManager {
                 tokio::time::sleep(tokio::time::Duration::from_millis(delay_ms)).await;
 
                 // Refresh the order to get updated authorization status
-                let _ = order.refresh().await?;
+                let _ = order.refresh().await.map_err(|e| AppError::Acme(e.to_string()))?;
 
                 // Re-fetch authorizations
-                let updated_authorizations = order.authorizations().await?;
+                let updated_authorizations = order
+                    .authorizations()
+                    .await
+                    .map_err(|e| AppError::Acme(e.to_string()))?;
 
                 // Match by comparing the first authorization (since we only have one domain)
                 // In a multi-domain scenario, you'd need to compare identifier values
                 let updated_authz = updated_authorizations
                     .first()
-                    .ok_or("Authorization not found")?;
+                    .ok_or_else(|| AppError::Acme("authorization not found".into()))?;
 
                 match updated_authz.status {
                     AuthorizationStatus::Valid => {
                         info!("✅ Challenge validated!");
+                        if challenge_type == ChallengeType::Dns01 {
+                            if let Some(provider) = &self.dns_provider {
+                                let bare_domain = self.domain.trim_start_matches("*.");
+                                let record_name = format!("_acme-challenge.{}", bare_domain);
+                                if let Err(e) = provider.delete_txt_record(&record_name).await {
+                                    warn!("Failed to clean up DNS-01 TXT record: {}", e);
+                                }
+                            }
+                        }
                         break;
                     }
                     AuthorizationStatus::Pending => {
                         tries += 1;
                         if tries > 30 {
-                            return Err("Challenge validation timeout".into());
+                            return Err(AppError::Acme("challenge validation timeout".into()));
                         }
                         delay_ms = std::cmp::min(delay_ms * 2, 5000); // Exponential backoff
                     }
                     AuthorizationStatus::Invalid => {
-                        return Err("Challenge validation failed - marked invalid".into());
+                        return Err(AppError::Acme(
+                            "challenge validation failed - marked invalid".into(),
+                        ));
                     }
                     _ => {
-                        return Err(format!(
-                            "Challenge validation failed - unexpected status: {:?}",
+                        return Err(AppError::Acme(format!(
+                            "challenge validation failed - unexpected status: {:?}",
                             updated_authz.status
-                        )
-                        .into());
+                        )));
                     }
                 }
             }
@@ -237,15 +665,23 @@ impl AcmeManager {
         // Generate CSR
         info!("🔑 Generating certificate signing request...");
 
-        // Generate CSR
-        info!("🔑 Generating certificate signing request...");
-
-        let params = CertificateParams::new(vec![self.domain.clone()])?;
-        let key_pair = KeyPair::generate()?;
-        let csr = params.serialize_request(&key_pair)?;
+        let mut params = CertificateParams::new(vec![self.domain.clone()])
+            .map_err(|e| AppError::Acme(e.to_string()))?;
+        if self.domain.starts_with("*.") {
+            // CA/Browser Forum baseline requirements disallow a wildcard in
+            // the CN, so wildcard certs carry the name only as a SAN entry.
+            params.distinguished_name = DistinguishedName::new();
+        }
+        let key_pair = KeyPair::generate().map_err(|e| AppError::Acme(e.to_string()))?;
+        let csr = params
+            .serialize_request(&key_pair)
+            .map_err(|e| AppError::Acme(e.to_string()))?;
 
         // Finalize order
-        order.finalize(&csr).await?;
+        order
+            .finalize(csr.der())
+            .await
+            .map_err(|e| AppError::Acme(e.to_string()))?;
 
         info!("⏳ Waiting for certificate issuance...");
 
@@ -254,23 +690,27 @@ impl AcmeManager {
         loop {
             tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
 
-            let order_state = order.refresh().await?;
+            let order_state = order.refresh().await.map_err(|e| AppError::Acme(e.to_string()))?;
             match order_state.status {
                 OrderStatus::Valid => break,
                 OrderStatus::Processing => {
                     tries += 1;
                     if tries > 30 {
-                        return Err("Certificate issuance timeout".into());
+                        return Err(AppError::Acme("certificate issuance timeout".into()));
                     }
                 }
-                _ => return Err("Order failed".into()),
+                _ => return Err(AppError::Acme("order failed".into())),
             }
         }
 
         // Download certificate
-        let cert_chain_pem: Option<String> = order.certificate().await?;
+        let cert_chain_pem: Option<String> = order
+            .certificate()
+            .await
+            .map_err(|e| AppError::Acme(e.to_string()))?;
 
-        let cert_chain_pem = cert_chain_pem.ok_or("Failed to download certificate")?;
+        let cert_chain_pem =
+            cert_chain_pem.ok_or_else(|| AppError::Acme("failed to download certificate".into()))?;
 
         // Extract private key
         let private_key_pem = key_pair.serialize_pem();
@@ -292,7 +732,7 @@ impl AcmeManager {
 async fn generate_attestation(
     paypal_client_id: &str,
     paypal_user_id: &str,
-) -> Result<String, Box<dyn std::error::Error>> {
+) -> Result<String, AppError> {
     // On AMD SEV-SNP, attestation is retrieved via /dev/sev-guest
     // Include PAYPAL_CLIENT_ID and PAYPAL_USER_ID in REPORT_DATA field
     // Format: PAYPAL_CLIENT_ID=<id>|PAYPAL_USER_ID=<id>
@@ -311,36 +751,43 @@ async fn generate_attestation(
                 "Failed to get SEV-SNP report: {}. Using mock attestation.",
                 e
             );
-            create_mock_attestation(paypal_client_id, paypal_user_id)
+            create_mock_attestation(&report_data)
         }
     };
 
     Ok(attestation_report)
 }
 
-fn get_sev_snp_report(report_data: &str) -> Result<String, Box<dyn std::error::Error>> {
+fn get_sev_snp_report(report_data: &str) -> Result<String, AppError> {
     // Use snpguest tool to get attestation report
     let output = std::process::Command::new("snpguest")
         .arg("report")
         .arg("--random")
         .arg("--report-data")
         .arg(report_data)
-        .output()?;
+        .output()
+        .map_err(|e| AppError::Attestation(e.to_string()))?;
 
     if !output.status.success() {
-        return Err("Failed to generate SNP attestation report".into());
+        return Err(AppError::Attestation(
+            "failed to generate SNP attestation report".into(),
+        ));
     }
 
-    let report = String::from_utf8(output.stdout)?;
+    let report =
+        String::from_utf8(output.stdout).map_err(|e| AppError::Attestation(e.to_string()))?;
     Ok(report)
 }
 
-fn create_mock_attestation(paypal_client_id: &str, paypal_user_id: &str) -> String {
+/// Builds the mock attestation report JSON used on hardware without
+/// `/dev/sev-guest` available, parameterized on the `REPORT_DATA` string the
+/// caller hashed a real attestation request around.
+fn create_mock_attestation(report_data: &str) -> String {
     // For testing on non-SEV hardware
     serde_json::json!({
         "type": "mock_attestation",
         "warning": "This is a mock attestation for testing purposes only",
-        "report_data": format!("PAYPAL_CLIENT_ID={}|PAYPAL_USER_ID={}", paypal_client_id, paypal_user_id),
+        "report_data": report_data,
         "measurement": "0000000000000000000000000000000000000000000000000000000000000000",
         "platform_version": "mock",
         "policy": "0x30000"
@@ -360,61 +807,150 @@ fn sha2_hash(data: &str) -> String {
 // ============================================================================
 
 async fn exchange_code_for_token(
+    client: &PayPalClient,
     code: &str,
     client_id: &str,
     client_secret: &str,
     redirect_uri: &str,
-) -> Result<TokenResponse, Box<dyn std::error::Error>> {
-    let client = reqwest::Client::new();
-
-    let params = [
-        ("grant_type", "authorization_code"),
-        ("code", code),
-        ("redirect_uri", redirect_uri),
-    ];
-
-    let response = client
-        .post("https://api.paypal.com/v1/oauth2/token")
-        .basic_auth(client_id, Some(client_secret))
-        .form(&params)
-        .send()
-        .await?;
+    env: &PayPalEnv,
+) -> Result<TokenResponse, AppError> {
+    let form_body = format!(
+        "grant_type=authorization_code&code={}&redirect_uri={}",
+        urlencoding::encode(code),
+        urlencoding::encode(redirect_uri)
+    );
+    let basic_auth =
+        base64::engine::general_purpose::STANDARD.encode(format!("{}:{}", client_id, client_secret));
+
+    client
+        .post_token(
+            &env.token_url(),
+            &[("Authorization", format!("Basic {}", basic_auth))],
+            form_body,
+        )
+        .await
+}
 
-    if !response.status().is_success() {
-        let error_text = response.text().await?;
-        return Err(format!("Token exchange failed: {}", error_text).into());
+async fn get_userinfo(
+    client: &PayPalClient,
+    access_token: &str,
+    env: &PayPalEnv,
+) -> Result<PayPalUserInfo, AppError> {
+    client
+        .get_json(
+            &env.userinfo_url(),
+            &[("Authorization", format!("Bearer {}", access_token))],
+        )
+        .await
+}
+
+// ============================================================================
+// APP ACCESS TOKEN CACHE
+// ============================================================================
+
+/// A cached `client_credentials` token, with enough bookkeeping to know when
+/// it needs refreshing.
+#[derive(Debug, Clone)]
+struct AccessToken {
+    access_token: String,
+    #[allow(dead_code)]
+    token_type: String,
+    expires_in: u64,
+    obtained_at: std::time::Instant,
+}
+
+impl AccessToken {
+    /// True once fewer than 60 seconds of validity remain.
+    fn is_expiring_soon(&self) -> bool {
+        self.obtained_at.elapsed().as_secs() + 60 >= self.expires_in
     }
+}
 
-    let token_response: TokenResponse = response.json().await?;
-    Ok(token_response)
+/// Lazily obtains and caches the app's own `client_credentials` token,
+/// refreshing it transparently once it's about to expire.
+struct Auth {
+    client_id: String,
+    client_secret: String,
+    env: PayPalEnv,
+    cached: RwLock<Option<AccessToken>>,
 }
 
-async fn get_userinfo(access_token: &str) -> Result<PayPalUserInfo, Box<dyn std::error::Error>> {
-    let client = reqwest::Client::new();
+impl Auth {
+    fn new(client_id: String, client_secret: String, env: PayPalEnv) -> Self {
+        Self {
+            client_id,
+            client_secret,
+            env,
+            cached: RwLock::new(None),
+        }
+    }
 
-    let response = client
-        .get("https://api.paypal.com/v1/identity/oauth2/userinfo?schema=paypalv1.1")
-        .bearer_auth(access_token)
-        .send()
-        .await?;
+    /// Returns the cached token if it's still fresh, otherwise performs the
+    /// `client_credentials` exchange and caches the result.
+    async fn get_token(&self, client: &reqwest::Client) -> Result<AccessToken, AppError> {
+        if let Some(token) = self.cached.read().clone() {
+            if !token.is_expiring_soon() {
+                return Ok(token);
+            }
+        }
+        self.refresh(client).await
+    }
 
-    if !response.status().is_success() {
-        let error_text = response.text().await?;
-        return Err(format!("Userinfo request failed: {}", error_text).into());
+    async fn refresh(&self, client: &reqwest::Client) -> Result<AccessToken, AppError> {
+        let response = client
+            .post(self.env.token_url())
+            .basic_auth(&self.client_id, Some(&self.client_secret))
+            .form(&[("grant_type", "client_credentials")])
+            .send()
+            .await
+            .map_err(|e| AppError::PayPal(transport_error(&e)))?;
+
+        if !response.status().is_success() {
+            let error_text = response
+                .text()
+                .await
+                .map_err(|e| AppError::PayPal(transport_error(&e)))?;
+            return Err(parse_paypal_error(&error_text));
+        }
+
+        let token_response: TokenResponse = response
+            .json()
+            .await
+            .map_err(|e| AppError::PayPal(transport_error(&e)))?;
+
+        let token = AccessToken {
+            access_token: token_response.access_token,
+            token_type: token_response.token_type,
+            expires_in: token_response.expires_in,
+            obtained_at: std::time::Instant::now(),
+        };
+
+        *self.cached.write() = Some(token.clone());
+        info!("🔑 Refreshed PayPal app access token (expires_in={}s)", token.expires_in);
+        Ok(token)
     }
+}
 
-    let userinfo: PayPalUserInfo = response.json().await?;
-    Ok(userinfo)
+/// Wraps a transport-level `reqwest` failure (no PayPal error body to parse) in `PaypalError`.
+fn transport_error(e: &reqwest::Error) -> PaypalError {
+    PaypalError {
+        name: "transport_error".to_string(),
+        message: e.to_string(),
+        debug_id: None,
+        details: Vec::new(),
+    }
 }
 
 // ============================================================================
 // OCI VAULT INTEGRATION
 // ============================================================================
 
-async fn fetch_secret_from_vault() -> Result<String, Box<dyn std::error::Error>> {
+async fn fetch_secret_from_vault() -> Result<String, AppError> {
     // Simplified implementation - in production use official OCI Rust SDK
-    let _secret_id = std::env::var("SECRET_OCID")?;
-    let _region = std::env::var("OCI_REGION")?;
+    let _secret_id = std::env::var("SECRET_OCID")
+        .map_err(|e| AppError::Vault(format!("SECRET_OCID must be set: {}", e)))?;
+    let _region = std::env::var("OCI_REGION")
+        .map_err(|e| AppError::Vault(format!("OCI_REGION must be set: {}", e)))?;
 
     info!("Fetching PayPal secret from OCI Vault using instance principals...");
 
@@ -429,8 +965,23 @@ async fn fetch_secret_from_vault() -> Result<String, Box<dyn std::error::Error>>
 // HTTP HANDLERS
 // ============================================================================
 
+/// Renders an error banner for `AppError`, surfacing PayPal's `issue`/`debug_id`
+/// when the failure came back from the PayPal API instead of a generic message.
+fn render_app_error_content(title: &str, err: &AppError) -> String {
+    let detail = match err {
+        AppError::PayPal(paypal_err) => html_escape::encode_text(&paypal_err.to_string()).to_string(),
+        other => html_escape::encode_text(&other.to_string()).to_string(),
+    };
+    format!(
+        r#"<div class="error"><h2>❌ {}</h2><p>{}</p></div>
+           <a href="/" class="btn">← Back to Home</a>"#,
+        html_escape::encode_text(title),
+        detail
+    )
+}
+
 async fn index(State(state): State<Arc<AppState>>) -> Html<String> {
-    let cert_status_html = r#"<span class="cert-status cert-ram">🟢 RAM ONLY (Fresh)</span>"#;
+    let cert_status_html = state.cert_provenance.badge_html();
 
     let content = format!(
         r#"
@@ -461,13 +1012,27 @@ async fn index(State(state): State<Arc<AppState>>) -> Html<String> {
     Html(HTML_TEMPLATE.replace("{{CONTENT}}", &content))
 }
 
-async fn login(State(state): State<Arc<AppState>>) -> Redirect {
-    let auth_url = format!(
-        "https://www.paypal.com/signin/authorize?client_id={}&response_type=code&scope=openid%20profile%20email&redirect_uri={}",
+#[derive(Deserialize)]
+struct LoginQuery {
+    /// The live-status WS session id (from `/ws`) this login is for, if the
+    /// client is watching. Round-tripped through PayPal as the OAuth `state`
+    /// param so `callback` knows which session to report into.
+    session_id: Option<String>,
+}
+
+async fn login(Query(query): Query<LoginQuery>, State(state): State<Arc<AppState>>) -> Redirect {
+    let mut auth_url = format!(
+        "{}?client_id={}&response_type=code&scope=openid%20profile%20email&redirect_uri={}",
+        state.paypal_env.authorize_url(),
         state.paypal_client_id,
         urlencoding::encode(&state.redirect_uri)
     );
 
+    if let Some(session_id) = query.session_id {
+        auth_url.push_str(&format!("&state={}", urlencoding::encode(&session_id)));
+        notify_ws_session(&state, &session_id, WsEvent::PaypalRedirect);
+    }
+
     Redirect::temporary(&auth_url)
 }
 
@@ -475,8 +1040,16 @@ async fn callback(
     Query(query): Query<CallbackQuery>,
     State(state): State<Arc<AppState>>,
 ) -> Response {
+    let session_id = query.state;
+    let notify = |event: WsEvent| {
+        if let Some(id) = &session_id {
+            notify_ws_session(&state, id, event);
+        }
+    };
+
     // Handle OAuth errors
     if let Some(error) = query.error {
+        notify(WsEvent::Failed { reason: error.clone() });
         let content = format!(
             r#"<div class="error"><h2>❌ Authentication Error</h2><p>{}</p></div>
                <a href="/" class="btn">← Back to Home</a>"#,
@@ -488,41 +1061,44 @@ async fn callback(
     let code = match query.code {
         Some(c) => c,
         None => {
+            notify(WsEvent::Failed { reason: "missing authorization code".to_string() });
             return (StatusCode::BAD_REQUEST, "Missing authorization code").into_response();
         }
     };
 
     // Exchange code for access token
     let token_response = match exchange_code_for_token(
+        &state.paypal_client,
         &code,
         &state.paypal_client_id,
         &state.paypal_client_secret,
         &state.redirect_uri,
+        &state.paypal_env,
     )
     .await
     {
         Ok(t) => t,
         Err(e) => {
             error!("Token exchange failed: {}", e);
-            let content = format!(
-                r#"<div class="error"><h2>❌ Token Exchange Failed</h2><p>{}</p></div>
-                   <a href="/" class="btn">← Back to Home</a>"#,
-                html_escape::encode_text(&e.to_string())
-            );
+            notify(WsEvent::Failed { reason: e.to_string() });
+            let content = render_app_error_content("Token Exchange Failed", &e);
             return Html(HTML_TEMPLATE.replace("{{CONTENT}}", &content)).into_response();
         }
     };
 
     // Get user info
-    let userinfo = match get_userinfo(&token_response.access_token).await {
+    let userinfo = match get_userinfo(
+        &state.paypal_client,
+        &token_response.access_token,
+        &state.paypal_env,
+    )
+    .await
+    {
         Ok(u) => u,
         Err(e) => {
             error!("Failed to get userinfo: {}", e);
-            let content = format!(
-                r#"<div class="error"><h2>❌ Failed to Get User Info</h2><p>{}</p></div>
-                   <a href="/" class="btn">← Back to Home</a>"#,
-                html_escape::encode_text(&e.to_string())
-            );
+            notify(WsEvent::Failed { reason: e.to_string() });
+            let content = render_app_error_content("Failed to Get User Info", &e);
             return Html(HTML_TEMPLATE.replace("{{CONTENT}}", &content)).into_response();
         }
     };
@@ -531,6 +1107,7 @@ async fn callback(
     {
         let mut used_ids = state.used_paypal_ids.write();
         if used_ids.contains(&userinfo.user_id) {
+            notify(WsEvent::Failed { reason: "PayPal account already used".to_string() });
             let content = r#"
                 <div class="error">
                     <h2>⚠️ Already Used</h2>
@@ -550,6 +1127,8 @@ async fn callback(
         );
     }
 
+    notify(WsEvent::Verified { user_id: userinfo.user_id.clone() });
+
     // Generate attestation report
     let attestation = match generate_attestation(&state.paypal_client_id, &userinfo.user_id).await {
         Ok(a) => a,
@@ -559,7 +1138,7 @@ async fn callback(
         }
     };
 
-    let cert_badge = r#"<span class="cert-ram">🟢 RAM ONLY (Fresh)</span>"#;
+    let cert_badge = state.cert_provenance.badge_html();
 
     let content = format!(
         r#"
@@ -604,6 +1183,418 @@ async fn acme_challenge(
         .map_err(|_| StatusCode::NOT_FOUND)
 }
 
+/// Basic runtime diagnostics, gated on mTLS: the connection's
+/// [`ClientIdentity`] extension is only `Some` when the client presented a
+/// cert that chained to the configured CA, so an anonymous caller (no
+/// `MTLS_CA_PEM_PATH` configured, or no cert presented) gets a 403 here even
+/// though the same TLS listener serves the public OAuth routes just fine.
+async fn admin_status(
+    State(state): State<Arc<AppState>>,
+    Extension(client_identity): Extension<Option<ClientIdentity>>,
+) -> Response {
+    let Some(identity) = client_identity else {
+        return (StatusCode::FORBIDDEN, "mTLS client certificate required").into_response();
+    };
+
+    info!("Admin diagnostics accessed by verified client '{}'", identity.subject);
+
+    Json(serde_json::json!({
+        "domain": state.domain,
+        "paypal_env": format!("{:?}", state.paypal_env),
+        "used_paypal_ids": state.used_paypal_ids.read().len(),
+        "client_subject": identity.subject,
+        "client_sans": identity.sans,
+    }))
+    .into_response()
+}
+
+// ============================================================================
+// LIVE STATUS WEBSOCKET
+// ============================================================================
+
+/// A structured status update pushed to a `/ws` client watching a login
+/// flow. Mirrors the stages `login`/`callback` actually go through, so the
+/// client never has to poll.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum WsEvent {
+    /// Sent once right after the socket opens, carrying the session id the
+    /// client should thread through to `/login?session_id=...`.
+    Pending { session_id: String },
+    /// `login()` redirected the browser to PayPal for this session.
+    PaypalRedirect,
+    /// `callback()` finished the OAuth exchange and accepted the PayPal id.
+    Verified { user_id: String },
+    /// `callback()` rejected or failed to complete the flow.
+    Failed { reason: String },
+}
+
+/// Pushes `event` to the WS client watching `session_id`, if one is still
+/// connected. Silently a no-op otherwise — nobody may be watching, and
+/// that's fine, since `/login`/`/callback` work the same either way.
+fn notify_ws_session(state: &AppState, session_id: &str, event: WsEvent) {
+    if let Some(sender) = state.ws_sessions.read().get(session_id) {
+        let _ = sender.send(event);
+    }
+}
+
+#[derive(Deserialize)]
+struct WsQuery {
+    session_id: Option<String>,
+}
+
+/// Upgrades to a WebSocket that streams [`WsEvent`]s for one login flow.
+/// Built on axum's `ws` extractor, which is itself a `tokio-tungstenite`
+/// `WebSocketStream` layered over the same TLS connection this request
+/// arrived on — nothing extra to plumb through the accept loop, since the
+/// `auto::Builder` HTTP/1.1 path already speaks upgrades.
+async fn ws_handler(
+    ws: axum::extract::ws::WebSocketUpgrade,
+    Query(query): Query<WsQuery>,
+    State(state): State<Arc<AppState>>,
+) -> Response {
+    let session_id = query.session_id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    ws.on_upgrade(move |socket| handle_ws_session(socket, state, session_id))
+}
+
+/// How often the server pings an idle WS connection to keep it alive
+/// through intermediate proxies/load balancers.
+const WS_PING_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+async fn handle_ws_session(mut socket: axum::extract::ws::WebSocket, state: Arc<AppState>, session_id: String) {
+    use axum::extract::ws::Message;
+
+    let (event_tx, mut event_rx) = tokio::sync::mpsc::unbounded_channel::<WsEvent>();
+    // Only the map holds a strong sender, so `event_rx` closes once a
+    // reconnect replaces it; the weak handle is just for identity checks.
+    let event_tx_weak = event_tx.downgrade();
+    state.ws_sessions.write().insert(session_id.clone(), event_tx);
+
+    // Only remove the map entry if it's still *our* sender: a reconnect for
+    // this session_id may have already replaced it with its own, and we must
+    // not delete the live connection's entry out from under it.
+    let remove_if_ours = |state: &AppState| {
+        let mut sessions = state.ws_sessions.write();
+        if sessions
+            .get(&session_id)
+            .is_some_and(|sender| {
+                event_tx_weak
+                    .upgrade()
+                    .is_some_and(|ours| sender.same_channel(&ours))
+            })
+        {
+            sessions.remove(&session_id);
+        }
+    };
+
+    let pending = serde_json::to_string(&WsEvent::Pending { session_id: session_id.clone() })
+        .expect("WsEvent always serializes");
+    if socket.send(Message::Text(pending)).await.is_err() {
+        remove_if_ours(&state);
+        return;
+    }
+
+    let mut ping_interval = tokio::time::interval(WS_PING_INTERVAL);
+    ping_interval.tick().await; // first tick fires immediately
+
+    loop {
+        tokio::select! {
+            incoming = socket.recv() => {
+                match incoming {
+                    Some(Ok(Message::Ping(data))) => {
+                        if socket.send(Message::Pong(data)).await.is_err() {
+                            break;
+                        }
+                    }
+                    Some(Ok(Message::Close(_))) | None => break,
+                    Some(Ok(_)) => {} // clients don't send anything else meaningful
+                    Some(Err(e)) => {
+                        warn!("WS session {} read error: {}", session_id, e);
+                        break;
+                    }
+                }
+            }
+            event = event_rx.recv() => {
+                let Some(event) = event else {
+                    break; // sender was replaced (session superseded) or dropped
+                };
+                let is_terminal = matches!(event, WsEvent::Verified { .. } | WsEvent::Failed { .. });
+                let payload = serde_json::to_string(&event).expect("WsEvent always serializes");
+                if socket.send(Message::Text(payload)).await.is_err() {
+                    break;
+                }
+                if is_terminal {
+                    let _ = socket.send(Message::Close(None)).await;
+                    break;
+                }
+            }
+            _ = ping_interval.tick() => {
+                if socket.send(Message::Ping(Vec::new())).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+
+    remove_if_ours(&state);
+}
+
+// ============================================================================
+// PAYPAL WEBHOOKS
+// ============================================================================
+
+/// Hosts PayPal is allowed to serve a webhook signing certificate from.
+/// Mirrors the allow-list PayPal's own SDKs use instead of building a
+/// bespoke chain-of-trust validator: the TLS handshake already proves the
+/// endpoint's certificate chains to a public root, so pinning the host is
+/// enough to stop a forged `cert-url` from pointing somewhere else.
+const TRUSTED_PAYPAL_CERT_HOSTS: &[&str] = &["api.paypal.com", "api.sandbox.paypal.com"];
+
+#[derive(Debug, Deserialize)]
+struct WebhookEvent {
+    id: String,
+    event_type: String,
+    #[serde(default)]
+    resource: serde_json::Value,
+}
+
+fn webhook_error(message: impl Into<String>) -> AppError {
+    AppError::PayPal(PaypalError {
+        name: "webhook_verification_failed".to_string(),
+        message: message.into(),
+        debug_id: None,
+        details: Vec::new(),
+    })
+}
+
+/// Cross-checks a signature-verified webhook against PayPal's own
+/// webhooks-events API, using the app's cached `client_credentials` token,
+/// so a transmission that somehow passes signature verification still can't
+/// lie about its `event_type` to a handler that trusts the body alone.
+async fn verify_event_with_paypal_api(state: &AppState, event: &WebhookEvent) -> Result<(), AppError> {
+    let token = state.auth.get_token(&state.http_client).await?;
+    let url = format!(
+        "{}/v1/notifications/webhooks-events/{}",
+        state.paypal_env.api_base(),
+        event.id
+    );
+
+    let response = state
+        .http_client
+        .get(&url)
+        .bearer_auth(&token.access_token)
+        .send()
+        .await
+        .map_err(|e| AppError::PayPal(transport_error(&e)))?;
+
+    if !response.status().is_success() {
+        let error_text = response
+            .text()
+            .await
+            .map_err(|e| AppError::PayPal(transport_error(&e)))?;
+        return Err(parse_paypal_error(&error_text));
+    }
+
+    let remote_event: WebhookEvent = response
+        .json()
+        .await
+        .map_err(|e| AppError::PayPal(transport_error(&e)))?;
+
+    if remote_event.event_type != event.event_type {
+        return Err(webhook_error(format!(
+            "event_type mismatch: transmission claimed {}, PayPal API reports {}",
+            event.event_type, remote_event.event_type
+        )));
+    }
+
+    Ok(())
+}
+
+/// Fetches (and caches) the DER-encoded leaf certificate PayPal signed this
+/// transmission with, refusing any `cert_url` that isn't one of PayPal's own
+/// API hosts.
+async fn fetch_webhook_cert(state: &AppState, cert_url: &str) -> Result<Vec<u8>, AppError> {
+    if let Some(cached) = state.webhook_cert_cache.read().get(cert_url) {
+        return Ok(cached.clone());
+    }
+
+    let url = reqwest::Url::parse(cert_url)
+        .map_err(|e| webhook_error(format!("invalid cert-url: {}", e)))?;
+    let host_is_trusted = url.scheme() == "https"
+        && url
+            .host_str()
+            .is_some_and(|host| TRUSTED_PAYPAL_CERT_HOSTS.contains(&host));
+    if !host_is_trusted {
+        return Err(webhook_error(format!(
+            "cert-url host is not a trusted PayPal endpoint: {}",
+            cert_url
+        )));
+    }
+
+    let pem = state
+        .http_client
+        .get(url)
+        .send()
+        .await
+        .map_err(|e| webhook_error(format!("failed to fetch signing cert: {}", e)))?
+        .bytes()
+        .await
+        .map_err(|e| webhook_error(format!("failed to read signing cert: {}", e)))?;
+
+    let certs: Vec<CertificateDer> = rustls_pemfile::certs(&mut &pem[..])
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| webhook_error(format!("failed to parse signing cert: {}", e)))?;
+    let leaf = certs
+        .first()
+        .ok_or_else(|| webhook_error("signing cert PEM contained no certificates"))?
+        .as_ref()
+        .to_vec();
+
+    state
+        .webhook_cert_cache
+        .write()
+        .insert(cert_url.to_string(), leaf.clone());
+
+    Ok(leaf)
+}
+
+/// Verifies `signature` over `signed_data` using the RSA public key embedded
+/// in `leaf_cert_der`, per `auth_algo` (PayPal currently only sends
+/// `SHA256withRSA`).
+fn verify_webhook_signature(
+    leaf_cert_der: &[u8],
+    auth_algo: &str,
+    signed_data: &[u8],
+    signature: &[u8],
+) -> Result<(), AppError> {
+    let (_, cert) = x509_parser::parse_x509_certificate(leaf_cert_der)
+        .map_err(|e| webhook_error(format!("failed to parse signing cert: {}", e)))?;
+
+    // For RSA keys the SPKI's BIT STRING payload is already the DER-encoded
+    // RSAPublicKey (modulus + exponent) that `ring` expects.
+    let public_key_bytes = cert.public_key().subject_public_key.data.as_ref();
+
+    let algorithm: &dyn ring::signature::VerificationAlgorithm = if auth_algo
+        .eq_ignore_ascii_case("SHA256withRSA")
+    {
+        &ring::signature::RSA_PKCS1_2048_8192_SHA256
+    } else {
+        return Err(webhook_error(format!(
+            "unsupported webhook auth algorithm: {}",
+            auth_algo
+        )));
+    };
+
+    ring::signature::UnparsedPublicKey::new(algorithm, public_key_bytes)
+        .verify(signed_data, signature)
+        .map_err(|_| webhook_error("signature verification failed"))
+}
+
+/// Generates an attestation whose `REPORT_DATA` hashes the full set of
+/// webhook event ids processed so far, so the VM can prove which events it
+/// handled without persisting them anywhere but RAM.
+async fn generate_webhook_attestation(event_ids: &[String]) -> Result<String, AppError> {
+    let report_data = format!("WEBHOOK_EVENT_IDS={}", event_ids.join(","));
+    let report_data_hash = sha2_hash(&report_data);
+
+    let attestation_report = match get_sev_snp_report(&report_data_hash) {
+        Ok(report) => report,
+        Err(e) => {
+            warn!(
+                "Failed to get SEV-SNP report: {}. Using mock attestation.",
+                e
+            );
+            create_mock_attestation(&report_data)
+        }
+    };
+
+    Ok(attestation_report)
+}
+
+async fn webhook(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+
+    let (transmission_id, transmission_time, cert_url, transmission_sig, auth_algo) = match (
+        header("PAYPAL-TRANSMISSION-ID"),
+        header("PAYPAL-TRANSMISSION-TIME"),
+        header("PAYPAL-CERT-URL"),
+        header("PAYPAL-TRANSMISSION-SIG"),
+        header("PAYPAL-AUTH-ALGO"),
+    ) {
+        (Some(id), Some(time), Some(cert_url), Some(sig), Some(algo)) => {
+            (id, time, cert_url, sig, algo)
+        }
+        _ => return (StatusCode::BAD_REQUEST, "Missing PayPal webhook headers").into_response(),
+    };
+
+    let mut crc32 = crc32fast::Hasher::new();
+    crc32.update(&body);
+    let signed_data = format!(
+        "{}|{}|{}|{}",
+        transmission_id,
+        transmission_time,
+        state.webhook_id,
+        crc32.finalize()
+    );
+
+    let result: Result<WebhookEvent, AppError> = async {
+        let leaf_cert = fetch_webhook_cert(&state, cert_url).await?;
+        let signature = base64::engine::general_purpose::STANDARD
+            .decode(transmission_sig)
+            .map_err(|e| webhook_error(format!("invalid transmission signature: {}", e)))?;
+        verify_webhook_signature(&leaf_cert, auth_algo, signed_data.as_bytes(), &signature)?;
+
+        serde_json::from_slice::<WebhookEvent>(&body)
+            .map_err(|e| webhook_error(format!("invalid webhook event body: {}", e)))
+    }
+    .await;
+
+    let event = match result {
+        Ok(event) => event,
+        Err(e) => {
+            warn!("Rejected PayPal webhook: {}", e);
+            return (StatusCode::BAD_REQUEST, e.to_string()).into_response();
+        }
+    };
+
+    info!(
+        "✅ Verified PayPal webhook event {} ({})",
+        event.id, event.event_type
+    );
+
+    if let Err(e) = verify_event_with_paypal_api(&state, &event).await {
+        warn!("Rejected PayPal webhook {}: {}", event.id, e);
+        return (StatusCode::BAD_REQUEST, e.to_string()).into_response();
+    }
+
+    // Don't hold the RwLock guard across the `.await` below.
+    let processed_event_ids = {
+        let mut processed = state.processed_webhook_events.write();
+        processed.push(event.id.clone());
+        processed.clone()
+    };
+
+    let attestation = match generate_webhook_attestation(&processed_event_ids).await {
+        Ok(a) => a,
+        Err(e) => {
+            error!("Failed to generate webhook attestation: {}", e);
+            format!("attestation generation failed: {}", e)
+        }
+    };
+
+    Json(serde_json::json!({
+        "status": "verified",
+        "event_id": event.id,
+        "event_type": event.event_type,
+        "attestation": attestation,
+    }))
+    .into_response()
+}
+
 // ============================================================================
 // TLS CONFIGURATION
 // ============================================================================
@@ -611,24 +1602,526 @@ async fn acme_challenge(
 async fn load_tls_config(
     cert_pem: &[u8],
     key_pem: &[u8],
-) -> Result<Arc<ServerConfig>, Box<dyn std::error::Error>> {
+) -> Result<Arc<ServerConfig>, AppError> {
     info!("Loading TLS configuration from RAM...");
 
-    let certs: Vec<CertificateDer> =
-        rustls_pemfile::certs(&mut &cert_pem[..]).collect::<Result<Vec<_>, _>>()?;
-
-    let key: PrivateKeyDer =
-        rustls_pemfile::private_key(&mut &key_pem[..])?.ok_or("No private key found")?;
+    let certs: Vec<CertificateDer> = rustls_pemfile::certs(&mut &cert_pem[..])
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| AppError::Tls(e.to_string()))?;
+
+    let key: PrivateKeyDer = rustls_pemfile::private_key(&mut &key_pem[..])
+        .map_err(|e| AppError::Tls(e.to_string()))?
+        .ok_or_else(|| AppError::Tls("no private key found".into()))?;
+
+    validate_key_matches_cert(&certs, &key)?;
+
+    let builder = ServerConfig::builder();
+    let mut config = match load_mtls_client_verifier().await? {
+        Some(verifier) => builder
+            .with_client_cert_verifier(verifier)
+            .with_single_cert(certs, key)
+            .map_err(|e| AppError::Tls(e.to_string()))?,
+        None => builder
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .map_err(|e| AppError::Tls(e.to_string()))?,
+    };
 
-    let config = ServerConfig::builder()
-        .with_no_client_auth()
-        .with_single_cert(certs, key)?;
+    config.alpn_protocols = if http2_only_enabled() {
+        vec![b"h2".to_vec()]
+    } else {
+        vec![b"h2".to_vec(), b"http/1.1".to_vec()]
+    };
 
     info!("✅ TLS configuration loaded successfully");
 
     Ok(Arc::new(config))
 }
 
+/// Whether to negotiate and serve `h2` exclusively, rejecting any client
+/// that doesn't advertise it. Deployments that want to rule out an HTTP/1.1
+/// downgrade entirely can opt into this instead of the default where
+/// `auto::Builder` serves whichever protocol the client negotiates.
+fn http2_only_enabled() -> bool {
+    std::env::var("HTTP2_ONLY")
+        .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+        .unwrap_or(false)
+}
+
+/// PEM path of the CA bundle client certificates must chain to for mTLS.
+/// Unset disables mTLS entirely, leaving the server exactly as before.
+fn mtls_ca_path() -> Option<PathBuf> {
+    std::env::var("MTLS_CA_PEM_PATH").ok().map(PathBuf::from)
+}
+
+/// Builds an "optional" client cert verifier from [`mtls_ca_path`]: an
+/// anonymous client is still let through (public OAuth routes keep working
+/// with no client cert at all), but a *presented* cert must chain to the
+/// configured CA, so only privileged routes that check the resulting
+/// [`ClientIdentity`] extension actually require one.
+async fn load_mtls_client_verifier(
+) -> Result<Option<Arc<dyn rustls::server::danger::ClientCertVerifier>>, AppError> {
+    let Some(ca_path) = mtls_ca_path() else {
+        return Ok(None);
+    };
+
+    let ca_pem = fs::read(&ca_path)
+        .await
+        .map_err(|e| AppError::Tls(format!("failed to read mTLS CA bundle: {}", e)))?;
+
+    let mut roots = rustls::RootCertStore::empty();
+    for cert in rustls_pemfile::certs(&mut &ca_pem[..]) {
+        let cert = cert.map_err(|e| AppError::Tls(format!("failed to parse mTLS CA bundle: {}", e)))?;
+        roots
+            .add(cert)
+            .map_err(|e| AppError::Tls(format!("invalid mTLS CA certificate: {}", e)))?;
+    }
+
+    let verifier = rustls::server::WebPkiClientVerifier::builder(Arc::new(roots))
+        .allow_unauthenticated()
+        .build()
+        .map_err(|e| AppError::Tls(format!("failed to build mTLS client verifier: {}", e)))?;
+
+    Ok(Some(verifier))
+}
+
+/// The verified identity of an mTLS client certificate, carried from the
+/// TLS layer into the request as an axum extension so handlers can gate
+/// access on it without re-parsing the peer chain themselves.
+#[derive(Debug, Clone)]
+struct ClientIdentity {
+    subject: String,
+    sans: Vec<String>,
+}
+
+/// Pulls the verified peer certificate (if the client presented one) out of
+/// `connection` and parses its subject/SANs. A `None` here means either no
+/// cert was presented, or it couldn't be parsed — both are treated the same
+/// as "anonymous" by privileged-route handlers.
+fn extract_client_identity(connection: &rustls::ServerConnection) -> Option<ClientIdentity> {
+    let leaf = connection.peer_certificates()?.first()?;
+    let (_, parsed) = x509_parser::parse_x509_certificate(leaf.as_ref()).ok()?;
+
+    let subject = parsed.subject().to_string();
+    let sans = parsed
+        .subject_alternative_name()
+        .ok()
+        .flatten()
+        .map(|ext| ext.value.general_names.iter().map(|name| name.to_string()).collect())
+        .unwrap_or_default();
+
+    Some(ClientIdentity { subject, sans })
+}
+
+/// Confirms `key` is actually usable as the signing key for `certs`'s leaf
+/// before the pair ever reaches rustls, so a mismatched cert/key rotation
+/// is rejected up front instead of surfacing as handshake failures later.
+fn validate_key_matches_cert(
+    certs: &[CertificateDer<'static>],
+    key: &PrivateKeyDer<'static>,
+) -> Result<(), AppError> {
+    let leaf = certs
+        .first()
+        .ok_or_else(|| AppError::Tls("certificate PEM contained no certificates".into()))?;
+    let signing_key = rustls::crypto::ring::sign::any_supported_type(key)
+        .map_err(|e| AppError::Tls(format!("unsupported private key: {}", e)))?;
+    rustls::sign::CertifiedKey::new(vec![leaf.clone()], signing_key)
+        .keys_match()
+        .map_err(|e| AppError::Tls(format!("private key does not match certificate: {}", e)))
+}
+
+/// Holds the live `ServerConfig` behind an `arc_swap::ArcSwap` so the accept
+/// loop can `load()` it on every single connection with no lock contention,
+/// while the renewal and file-watch tasks swap in a new one without
+/// dropping in-flight connections, which keep the `Arc` they already loaded.
+struct TlsConfigStore(arc_swap::ArcSwap<ServerConfig>);
+
+impl TlsConfigStore {
+    fn new(config: Arc<ServerConfig>) -> Self {
+        Self(arc_swap::ArcSwap::new(config))
+    }
+
+    fn load(&self) -> Arc<ServerConfig> {
+        self.0.load_full()
+    }
+
+    fn swap(&self, config: Arc<ServerConfig>) {
+        self.0.store(config);
+    }
+}
+
+/// Runs forever, waking up daily to renew the certificate once it enters the
+/// renewal window and atomically swapping the new config into `tls_store`.
+async fn run_certificate_renewal_task(
+    acme: AcmeManager,
+    cert_store: CertStore,
+    tls_store: Arc<TlsConfigStore>,
+) {
+    let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(24 * 60 * 60));
+    interval.tick().await; // first tick fires immediately; the cert was just issued/loaded
+
+    loop {
+        interval.tick().await;
+        info!("🔁 Checking certificate expiry for renewal...");
+
+        let Some((cert_pem, _)) = cert_store.load().await else {
+            warn!("No certificate found on disk during renewal check");
+            continue;
+        };
+
+        let days_remaining = match days_until_expiry(&cert_pem) {
+            Ok(days) => days,
+            Err(e) => {
+                warn!("Failed to parse certificate during renewal check: {}", e);
+                continue;
+            }
+        };
+
+        if days_remaining > CERT_RENEWAL_WINDOW_DAYS {
+            info!("Certificate has {} days remaining, no renewal needed", days_remaining);
+            continue;
+        }
+
+        info!("Certificate expires in {} days, renewing...", days_remaining);
+        let (cert_pem, key_pem) = match acme.obtain_new_certificate().await {
+            Ok(pair) => pair,
+            Err(e) => {
+                error!("Certificate renewal failed: {}", e);
+                continue;
+            }
+        };
+
+        if let Err(e) = cert_store.save(&cert_pem, &key_pem).await {
+            error!("Failed to persist renewed certificate: {}", e);
+            continue;
+        }
+
+        match load_tls_config(&cert_pem, &key_pem).await {
+            Ok(new_config) => {
+                tls_store.swap(new_config);
+                info!("✅ Certificate renewed and swapped into the live TLS listener");
+            }
+            Err(e) => error!("Failed to load renewed TLS config: {}", e),
+        }
+    }
+}
+
+/// How often to poll the cert/key files' mtimes for out-of-band rotations.
+const TLS_FILE_WATCH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Returns the mTLS CA bundle's mtime, if mTLS is configured, so the file
+/// watcher below can treat a CA-only rotation the same as a cert/key one.
+async fn mtls_ca_modified() -> Option<std::time::SystemTime> {
+    let ca_path = mtls_ca_path()?;
+    fs::metadata(&ca_path).await.ok()?.modified().ok()
+}
+
+/// Runs forever, polling the cert/key and (if configured) mTLS CA bundle's
+/// mtimes and hot-swapping `tls_store` whenever any of them changes on
+/// disk. This catches a rotation performed by something other than this
+/// process — an operator, a sidecar — without waiting for the
+/// expiry-driven renewal task above.
+async fn run_tls_file_watch_task(cert_store: CertStore, tls_store: Arc<TlsConfigStore>) {
+    let mut last_modified = (cert_store.modified().await, mtls_ca_modified().await);
+
+    loop {
+        tokio::time::sleep(TLS_FILE_WATCH_INTERVAL).await;
+
+        let modified = (cert_store.modified().await, mtls_ca_modified().await);
+        if modified.0.is_none() || modified == last_modified {
+            continue;
+        }
+        last_modified = modified;
+
+        let Some((cert_pem, key_pem)) = cert_store.load().await else {
+            warn!("TLS cert/key files changed but could not be read, keeping old config");
+            continue;
+        };
+
+        match load_tls_config(&cert_pem, &key_pem).await {
+            Ok(new_config) => {
+                tls_store.swap(new_config);
+                info!("✅ Detected cert/key change on disk, swapped into the live TLS listener");
+            }
+            Err(e) => error!(
+                "Cert/key files changed but failed to load, keeping previous TLS config: {}",
+                e
+            ),
+        }
+    }
+}
+
+// ============================================================================
+// OUTBOUND HTTPS CLIENT (PayPalClient)
+// ============================================================================
+
+/// Where [`PayPalClient`] sources its TLS trust anchors from, selected once
+/// at startup via `PAYPAL_TLS_TRUST`: `native` (OS trust store, the
+/// default), `webpki` (Mozilla's bundled roots), or any other value treated
+/// as a path to a CA bundle pinned specifically for PayPal's endpoints.
+#[derive(Debug, Clone)]
+enum TrustSource {
+    Native,
+    WebPkiRoots,
+    Custom(PathBuf),
+}
+
+impl TrustSource {
+    fn from_env() -> Self {
+        match std::env::var("PAYPAL_TLS_TRUST") {
+            Ok(v) if v.eq_ignore_ascii_case("native") => TrustSource::Native,
+            Ok(v) if v.eq_ignore_ascii_case("webpki") => TrustSource::WebPkiRoots,
+            Ok(path) => TrustSource::Custom(PathBuf::from(path)),
+            Err(_) => TrustSource::Native,
+        }
+    }
+}
+
+/// Dev-only escape hatch that disables outbound certificate verification
+/// entirely, e.g. to point [`PayPalClient`] at a self-signed sandbox
+/// double. Off by default, and refuses to be quiet about it when enabled.
+fn dangerous_tls_verification_disabled() -> bool {
+    let enabled = std::env::var("PAYPAL_TLS_DANGEROUS_DISABLE_VERIFY")
+        .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+        .unwrap_or(false);
+    if enabled {
+        error!(
+            "⚠️⚠️⚠️ PAYPAL_TLS_DANGEROUS_DISABLE_VERIFY is set: outbound PayPal TLS \
+             certificate verification is DISABLED. This must never run in production."
+        );
+    }
+    enabled
+}
+
+/// A `ServerCertVerifier` that accepts any certificate, used only behind
+/// [`dangerous_tls_verification_disabled`].
+#[derive(Debug)]
+struct NoCertificateVerification(Arc<rustls::crypto::CryptoProvider>);
+
+impl rustls::client::danger::ServerCertVerifier for NoCertificateVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &rustls::pki_types::ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: rustls::pki_types::UnixTime,
+    ) -> Result<rustls::client::danger::ServerCertVerified, rustls::Error> {
+        Ok(rustls::client::danger::ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+            .map(|_| rustls::client::danger::HandshakeSignatureValid::assertion())
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+            .map(|_| rustls::client::danger::HandshakeSignatureValid::assertion())
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+/// Builds the outbound `rustls::ClientConfig` for [`PayPalClient`] per the
+/// configured [`TrustSource`], or a verification-disabled config when
+/// [`dangerous_tls_verification_disabled`].
+async fn build_paypal_tls_config() -> Result<rustls::ClientConfig, AppError> {
+    if dangerous_tls_verification_disabled() {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        return Ok(rustls::ClientConfig::builder()
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(NoCertificateVerification(provider)))
+            .with_no_client_auth());
+    }
+
+    let mut roots = rustls::RootCertStore::empty();
+    match TrustSource::from_env() {
+        TrustSource::Native => {
+            let loaded = rustls_native_certs::load_native_certs();
+            for err in &loaded.errors {
+                warn!("Skipping unreadable native root certificate: {}", err);
+            }
+            for cert in loaded.certs {
+                roots
+                    .add(cert)
+                    .map_err(|e| AppError::Tls(format!("invalid native root cert: {}", e)))?;
+            }
+            info!("PayPalClient trusting the OS native root store");
+        }
+        TrustSource::WebPkiRoots => {
+            roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+            info!("PayPalClient trusting the bundled webpki-roots");
+        }
+        TrustSource::Custom(path) => {
+            let pem = fs::read(&path)
+                .await
+                .map_err(|e| AppError::Tls(format!("failed to read PayPal CA pin: {}", e)))?;
+            for cert in rustls_pemfile::certs(&mut &pem[..]) {
+                let cert =
+                    cert.map_err(|e| AppError::Tls(format!("failed to parse PayPal CA pin: {}", e)))?;
+                roots
+                    .add(cert)
+                    .map_err(|e| AppError::Tls(format!("invalid PayPal CA pin cert: {}", e)))?;
+            }
+            info!("PayPalClient pinned to custom CA bundle at {}", path.display());
+        }
+    }
+
+    Ok(rustls::ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth())
+}
+
+fn paypal_client_error(message: impl Into<String>) -> AppError {
+    AppError::PayPal(PaypalError {
+        name: "paypal_client_error".to_string(),
+        message: message.into(),
+        debug_id: None,
+        details: Vec::new(),
+    })
+}
+
+/// A small, pooled HTTPS client dedicated to server-to-server PayPal API
+/// calls (the OAuth token exchange and userinfo lookup), so its trust
+/// anchors can be pinned independently of whatever TLS backend `reqwest`'s
+/// defaults happen to use elsewhere in the app.
+struct PayPalClient {
+    client: hyper_util::client::legacy::Client<
+        hyper_rustls::HttpsConnector<hyper_util::client::legacy::connect::HttpConnector>,
+        http_body_util::Full<Bytes>,
+    >,
+}
+
+impl PayPalClient {
+    async fn new() -> Result<Self, AppError> {
+        let tls_config = build_paypal_tls_config().await?;
+
+        let builder = hyper_rustls::HttpsConnectorBuilder::new().with_tls_config(tls_config);
+        // A `Mock` environment points at a plain-http test double (e.g.
+        // wiremock); every other environment is held to HTTPS.
+        let builder = if matches!(PayPalEnv::from_env(), PayPalEnv::Mock(_)) {
+            builder.https_or_http()
+        } else {
+            builder.https_only()
+        };
+        let https = builder.enable_http1().build();
+
+        let client =
+            hyper_util::client::legacy::Builder::new(hyper_util::rt::TokioExecutor::new()).build(https);
+
+        Ok(Self { client })
+    }
+
+    /// POSTs an `application/x-www-form-urlencoded` body to `url` and
+    /// parses the response as JSON `T` — shaped for the PayPal OAuth token
+    /// exchange, the outbound call most worth pinning a trust source for.
+    async fn post_token<T: serde::de::DeserializeOwned>(
+        &self,
+        url: &str,
+        headers: &[(&str, String)],
+        form_body: String,
+    ) -> Result<T, AppError> {
+        let mut builder = hyper::Request::builder()
+            .method(hyper::Method::POST)
+            .uri(url)
+            .header(hyper::header::CONTENT_TYPE, "application/x-www-form-urlencoded");
+        for (name, value) in headers {
+            builder = builder.header(*name, value);
+        }
+
+        let request = builder
+            .body(http_body_util::Full::new(Bytes::from(form_body)))
+            .map_err(|e| paypal_client_error(format!("failed to build request: {}", e)))?;
+
+        self.send_json(request).await
+    }
+
+    /// GETs `url` with the given headers and parses the response as JSON
+    /// `T` — shaped for the PayPal userinfo lookup that follows the token
+    /// exchange.
+    async fn get_json<T: serde::de::DeserializeOwned>(
+        &self,
+        url: &str,
+        headers: &[(&str, String)],
+    ) -> Result<T, AppError> {
+        let mut builder = hyper::Request::builder().method(hyper::Method::GET).uri(url);
+        for (name, value) in headers {
+            builder = builder.header(*name, value);
+        }
+
+        let request = builder
+            .body(http_body_util::Full::new(Bytes::new()))
+            .map_err(|e| paypal_client_error(format!("failed to build request: {}", e)))?;
+
+        self.send_json(request).await
+    }
+
+    async fn send_json<T: serde::de::DeserializeOwned>(
+        &self,
+        request: hyper::Request<http_body_util::Full<Bytes>>,
+    ) -> Result<T, AppError> {
+        let response = self
+            .client
+            .request(request)
+            .await
+            .map_err(|e| paypal_client_error(format!("request failed: {}", e)))?;
+
+        let status = response.status();
+        let body = http_body_util::BodyExt::collect(response.into_body())
+            .await
+            .map_err(|e| paypal_client_error(format!("failed to read response body: {}", e)))?
+            .to_bytes();
+
+        if !status.is_success() {
+            return Err(parse_paypal_error(&String::from_utf8_lossy(&body)));
+        }
+
+        serde_json::from_slice(&body)
+            .map_err(|e| paypal_client_error(format!("failed to parse response: {}", e)))
+    }
+}
+
+// ============================================================================
+// GRACEFUL SHUTDOWN
+// ============================================================================
+
+/// How long outstanding connections get to finish a graceful shutdown
+/// before the process exits out from under them anyway.
+const SHUTDOWN_GRACE_PERIOD: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Resolves once SIGINT or SIGTERM is received, marking `shutdown_tx` so
+/// every `select!` watching it (the accept loop, each connection) notices.
+async fn wait_for_shutdown_signal(shutdown_tx: tokio::sync::watch::Sender<bool>) {
+    match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+        Ok(mut sigterm) => tokio::select! {
+            _ = tokio::signal::ctrl_c() => info!("Received SIGINT"),
+            _ = sigterm.recv() => info!("Received SIGTERM"),
+        },
+        Err(e) => {
+            error!("Failed to install SIGTERM handler, waiting for SIGINT only: {}", e);
+            let _ = tokio::signal::ctrl_c().await;
+            info!("Received SIGINT");
+        }
+    }
+
+    let _ = shutdown_tx.send(true);
+    // Dropping the sender would make every later `changed()` return at once.
+    shutdown_tx.closed().await;
+}
+
 // ============================================================================
 // MAIN
 // ============================================================================
@@ -648,25 +2141,46 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .expect("PAYPAL_CLIENT_ID must be set in instance metadata");
 
     let domain = std::env::var("DOMAIN").expect("DOMAIN must be set");
+    let paypal_env = PayPalEnv::from_env();
+    info!("🌐 PayPal environment: {:?}", paypal_env);
 
     let redirect_uri = format!("https://{}/callback", domain);
 
     // Fetch PAYPAL_SECRET from OCI Vault using instance principals
     let paypal_client_secret = fetch_secret_from_vault().await?;
 
-    // Handle ACME certificate acquisition
+    // Handle ACME certificate acquisition, reusing what's on the encrypted
+    // disk if it's still comfortably valid
     let acme = AcmeManager::new(domain.clone());
-    let (cert_pem, key_pem) = acme.ensure_certificate().await?;
+    let cert_store = CertStore::from_env();
+    let (cert_pem, key_pem, cert_provenance) = acme.ensure_certificate(&cert_store).await?;
 
-    info!("🟢 Certificate: RAM ONLY (freshly obtained from Let's Encrypt)");
+    info!("🟢 Certificate ready (disk-persisted, renewed automatically before expiry)");
 
     // Initialize application state
+    let http_client = reqwest::Client::new();
+    let paypal_client = Arc::new(PayPalClient::new().await?);
+    let auth = Arc::new(Auth::new(
+        paypal_client_id.clone(),
+        paypal_client_secret.clone(),
+        paypal_env.clone(),
+    ));
+    let webhook_id = std::env::var("PAYPAL_WEBHOOK_ID").unwrap_or_default();
     let state = Arc::new(AppState {
         paypal_client_id: paypal_client_id.clone(),
         paypal_client_secret,
         redirect_uri,
         used_paypal_ids: Arc::new(RwLock::new(HashSet::new())),
         domain: domain.clone(),
+        paypal_env,
+        cert_provenance,
+        http_client,
+        paypal_client,
+        auth,
+        webhook_id,
+        webhook_cert_cache: Arc::new(RwLock::new(HashMap::new())),
+        processed_webhook_events: Arc::new(RwLock::new(Vec::new())),
+        ws_sessions: Arc::new(RwLock::new(HashMap::new())),
     });
 
     // Build router
@@ -674,12 +2188,24 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .route("/", get(index))
         .route("/login", get(login))
         .route("/callback", get(callback))
+        .route("/webhook", post(webhook))
+        .route("/admin/status", get(admin_status))
+        .route("/ws", get(ws_handler))
         .route("/.well-known/acme-challenge/:token", get(acme_challenge))
         .layer(TraceLayer::new_for_http())
         .with_state(state);
 
-    // Load TLS configuration
+    // Load TLS configuration and keep it behind a swappable store so the
+    // renewal and file-watch tasks can rotate it without restarting the listener
     let tls_config = load_tls_config(&cert_pem, &key_pem).await?;
+    let tls_store = Arc::new(TlsConfigStore::new(tls_config));
+
+    tokio::spawn(run_certificate_renewal_task(
+        AcmeManager::new(domain.clone()),
+        cert_store.clone(),
+        tls_store.clone(),
+    ));
+    tokio::spawn(run_tls_file_watch_task(cert_store, tls_store.clone()));
 
     // Start HTTPS server
     let addr = SocketAddr::from(([0, 0, 0, 0], 443));
@@ -688,15 +2214,42 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     info!("✅ System ready to accept PayPal OAuth authentication");
 
     let listener = TcpListener::bind(addr).await?;
+    let http2_only = http2_only_enabled();
+    if http2_only {
+        info!("🔒 HTTP/2-only mode: rejecting connections that don't negotiate h2");
+    }
+
+    // Signals a shutdown to the accept loop and every in-flight connection
+    // at once; `changed()` lets each `select!` below notice it without
+    // polling.
+    let (shutdown_tx, mut shutdown_rx) = tokio::sync::watch::channel(false);
+    tokio::spawn(wait_for_shutdown_signal(shutdown_tx));
+
+    // Tracks every spawned connection task so shutdown can wait for them
+    // (bounded by SHUTDOWN_GRACE_PERIOD) instead of dropping them mid-flight.
+    let tracker = tokio_util::task::TaskTracker::new();
 
     // Serve with TLS using hyper
     loop {
-        let (tcp_stream, _remote_addr) = listener.accept().await?;
+        let tcp_stream = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _remote_addr)) => stream,
+                Err(e) => {
+                    error!("Failed to accept connection: {}", e);
+                    continue;
+                }
+            },
+            _ = shutdown_rx.changed() => {
+                info!("🛑 Shutdown signal received, no longer accepting new connections");
+                break;
+            }
+        };
 
-        let tls_acceptor = tokio_rustls::TlsAcceptor::from(tls_config.clone());
+        let tls_acceptor = tokio_rustls::TlsAcceptor::from(tls_store.load());
         let app_clone = app.clone();
+        let mut conn_shutdown_rx = shutdown_rx.clone();
 
-        tokio::spawn(async move {
+        tracker.spawn(async move {
             let tls_stream = match tls_acceptor.accept(tcp_stream).await {
                 Ok(stream) => stream,
                 Err(e) => {
@@ -705,20 +2258,87 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 }
             };
 
+            if http2_only {
+                let negotiated_h2 = tls_stream
+                    .get_ref()
+                    .1
+                    .alpn_protocol()
+                    .is_some_and(|proto| proto == b"h2");
+                if !negotiated_h2 {
+                    warn!("Rejecting connection that didn't negotiate h2 in HTTP/2-only mode");
+                    return;
+                }
+            }
+
+            // The verifier runs in "optional" mode, so a connection reaching
+            // this point either presented no client cert or one that chains
+            // to the configured mTLS CA; either way it's safe to keep serving
+            // and let privileged routes gate on the identity themselves.
+            let client_identity = extract_client_identity(&tls_stream.get_ref().1);
+
             let io = hyper_util::rt::TokioIo::new(tls_stream);
 
-            let service = hyper::service::service_fn(move |req| {
+            let service = hyper::service::service_fn(move |mut req| {
                 let app = app_clone.clone();
-                async move { Ok::<_, std::convert::Infallible>(app.clone().oneshot(req).await.unwrap()) }
+                let client_identity = client_identity.clone();
+                async move {
+                    req.extensions_mut().insert(client_identity);
+                    Ok::<_, std::convert::Infallible>(app.clone().oneshot(req).await.unwrap())
+                }
             });
 
-            if let Err(e) =
-                hyper_util::server::conn::auto::Builder::new(hyper_util::rt::TokioExecutor::new())
-                    .serve_connection(io, service)
-                    .await
-            {
-                error!("Error serving connection: {}", e);
+            // Keep polling the same connection future across iterations: a
+            // shutdown signal just calls `graceful_shutdown()` on it and
+            // loops back to let it finish the in-flight request/response.
+            if http2_only {
+                let mut conn = std::pin::pin!(
+                    hyper::server::conn::http2::Builder::new(hyper_util::rt::TokioExecutor::new())
+                        .serve_connection(io, service)
+                );
+                loop {
+                    tokio::select! {
+                        res = conn.as_mut() => {
+                            if let Err(e) = res {
+                                error!("Error serving connection: {}", e);
+                            }
+                            break;
+                        }
+                        _ = conn_shutdown_rx.changed() => {
+                            conn.as_mut().graceful_shutdown();
+                        }
+                    }
+                }
+            } else {
+                let mut conn = std::pin::pin!(
+                    hyper_util::server::conn::auto::Builder::new(hyper_util::rt::TokioExecutor::new())
+                        .serve_connection_with_upgrades(io, service)
+                );
+                loop {
+                    tokio::select! {
+                        res = conn.as_mut() => {
+                            if let Err(e) = res {
+                                error!("Error serving connection: {}", e);
+                            }
+                            break;
+                        }
+                        _ = conn_shutdown_rx.changed() => {
+                            conn.as_mut().graceful_shutdown();
+                        }
+                    }
+                }
             }
         });
     }
+
+    tracker.close();
+    info!(
+        "⏳ Waiting up to {}s for in-flight connections to finish...",
+        SHUTDOWN_GRACE_PERIOD.as_secs()
+    );
+    if tokio::time::timeout(SHUTDOWN_GRACE_PERIOD, tracker.wait()).await.is_err() {
+        warn!("Shutdown grace period elapsed with connections still open; aborting them");
+    }
+    info!("👋 Shutdown complete");
+
+    Ok(())
 }